@@ -2,26 +2,26 @@
 // This can be deployed as a separate service/container
 use log_pipelines::config::Config;
 use log_pipelines::drainer::start_drainer;
-use std::sync::Arc;
+use log_pipelines::file_redis_layer::create_redis_pool;
+use log_pipelines::log_store::RedisLogStore;
 
 #[tokio::main]
 async fn main() {
     println!("🔄 Starting Log Pipeline Drainer Service");
     println!("========================================\n");
-    
+
     // Load configuration
     let config = Config::load();
-    
-    // Connect to Redis
-    let redis_client = redis::Client::open(config.redis.url.as_str())
-        .expect("Failed to connect to Redis");
-    
+
+    // Create a pooled Redis connection manager, shared across drain cycles
+    let redis_pool = create_redis_pool(&config.redis);
+
     // Test connection
-    let _test_conn = redis_client
-        .get_async_connection()
+    let _test_conn = redis_pool
+        .get()
         .await
-        .expect("Failed to get Redis connection");
-    
+        .expect("Failed to get Redis connection from pool");
+
     println!("✅ Connected to Redis at {}", config.redis.url);
     println!(
         "🔄 Drainer will run every {} seconds",
@@ -29,9 +29,9 @@ async fn main() {
     );
     println!("🔍 Looking for keys matching: {}", config.drainer.log_pattern);
     println!("\nPress Ctrl+C to stop the drainer\n");
-    
+
     // Start the drainer (this runs forever)
-    let drainer_redis = Arc::new(redis_client);
-    start_drainer(drainer_redis, config.drainer).await;
+    let store = RedisLogStore::new(redis_pool);
+    start_drainer(store, config.drainer).await;
 }
 
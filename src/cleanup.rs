@@ -1,75 +1,133 @@
 // Optional cleanup service - removes old keys that drainer might have missed
 // This is a safety net in case drainer fails or misses keys
 use crate::config::Config;
-use redis::AsyncCommands;
-use std::sync::Arc;
+use crate::log_store::LogStore;
 use tokio::time::{interval, Duration};
 
 /// Cleanup service - removes very old keys as a safety net
 /// This runs less frequently than the drainer and only removes keys
 /// that are very old (approaching TTL expiration)
-/// 
+///
 /// This prevents Redis memory bloat if drainer somehow misses keys
-pub async fn start_cleanup_service(
-    redis_client: Arc<redis::Client>,
-    config: Config,
-) {
+///
+/// Generic over `LogStore` like the drainer, so it shares the same pooled
+/// backend (and can be driven by the in-memory mock in tests) instead of
+/// opening its own connections.
+///
+/// Sweeps the keyspace page by page with `SCAN` rather than a single
+/// blocking `KEYS` call, checking each page's `LLEN` and yielding to the
+/// scheduler before fetching the next page. This keeps memory bounded to
+/// one page at a time and avoids stalling Redis's single-threaded event
+/// loop on a keyspace with millions of keys.
+pub async fn start_cleanup_service<S: LogStore>(store: S, config: Config) {
     // Only run if TTL is disabled (otherwise TTL handles cleanup)
     if config.redis.disable_ttl {
         println!("🔄 Starting cleanup service (TTL disabled - cleanup needed)");
-        
+
         // Run cleanup every 1 hour
         let mut interval_timer = interval(Duration::from_secs(3600));
-        
+
         loop {
             interval_timer.tick().await;
-            
-            println!("🧹 Cleanup: Starting cleanup cycle...");
-            
-            let mut conn = match redis_client.get_async_connection().await {
-                Ok(conn) => conn,
-                Err(e) => {
-                    eprintln!("❌ Cleanup: Failed to get Redis connection: {}", e);
-                    continue;
+            run_cleanup_cycle(&store, &config).await;
+        }
+    } else {
+        println!("ℹ️  Cleanup service not needed (TTL enabled - handles cleanup automatically)");
+    }
+}
+
+/// A single cleanup cycle: page through matching keys with `SCAN`,
+/// checking `LLEN` on each one. Returns the number of keys found with
+/// undrained logs. Split out from `start_cleanup_service` so it can be
+/// driven directly (and its outcome asserted on) from tests without
+/// waiting on the interval timer.
+async fn run_cleanup_cycle<S: LogStore>(store: &S, config: &Config) -> usize {
+    println!("🧹 Cleanup: Starting cleanup cycle...");
+
+    let mut old_keys = 0;
+    let mut cursor: u64 = 0;
+
+    loop {
+        let (next_cursor, keys) = match store
+            .scan(cursor, &config.drainer.log_pattern, config.cleanup.scan_count)
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                eprintln!("❌ Cleanup: Failed to scan keys: {:?}", e);
+                break;
+            }
+        };
+
+        // For each key in this page, check whether it still holds logs (its
+        // age is not checked). A non-empty key means the drainer may have
+        // missed it - but we'll be conservative and only log warnings.
+        for key in &keys {
+            match store.llen(key).await {
+                Ok(length) if length > 0 => {
+                    old_keys += 1;
+                    eprintln!(
+                        "⚠️  Cleanup: Key {} has {} logs and hasn't been drained (drainer may have issues)",
+                        key, length
+                    );
                 }
-            };
-            
-            // Find all keys matching our log pattern
-            let keys: Vec<String> = match conn.keys(&config.drainer.log_pattern).await {
-                Ok(keys) => keys,
+                Ok(_) => {}
                 Err(e) => {
-                    eprintln!("❌ Cleanup: Failed to get keys: {}", e);
-                    continue;
-                }
-            };
-            
-            // For each key, check if it's very old (older than 1 hour)
-            // If drainer hasn't processed it in 1 hour, something might be wrong
-            // But we'll be conservative and only log warnings
-            let mut old_keys = 0;
-            for key in &keys {
-                // Get list length to see if key has data
-                let len: Result<usize, _> = conn.llen(key).await;
-                if let Ok(length) = len {
-                    if length > 0 {
-                        old_keys += 1;
-                        eprintln!(
-                            "⚠️  Cleanup: Key {} has {} logs and hasn't been drained (drainer may have issues)",
-                            key, length
-                        );
-                    }
+                    eprintln!("❌ Cleanup: Failed to check length of {}: {:?}", key, e);
                 }
             }
-            
-            if old_keys > 0 {
-                println!("🧹 Cleanup: Found {} keys with undrained logs", old_keys);
-                println!("   These should be handled by the drainer. Check drainer logs for issues.");
-            } else {
-                println!("✅ Cleanup: All keys are clean");
-            }
         }
+
+        cursor = next_cursor;
+        if cursor == 0 {
+            break;  // SCAN signals a full pass over the keyspace with cursor 0
+        }
+
+        // Yield between pages so a huge keyspace sweep doesn't monopolize
+        // the scheduler or stall other tasks sharing this runtime.
+        tokio::task::yield_now().await;
+    }
+
+    if old_keys > 0 {
+        println!("🧹 Cleanup: Found {} keys with undrained logs", old_keys);
+        println!("   These should be handled by the drainer. Check drainer logs for issues.");
     } else {
-        println!("ℹ️  Cleanup service not needed (TTL enabled - handles cleanup automatically)");
+        println!("✅ Cleanup: All keys are clean");
     }
+
+    old_keys
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_store::MockLogStore;
+
+    fn test_config() -> Config {
+        let mut config = Config::default();
+        config.drainer.log_pattern = "logs:user_*:*".to_string();
+        config.cleanup.scan_count = 10;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_cycle_reports_keys_with_undrained_logs() {
+        let store = MockLogStore::new();
+        store.push("logs:user_1:100", "undrained").await.unwrap();
+        store.push("logs:user_2:100", "also undrained").await.unwrap();
+
+        let old_keys = run_cleanup_cycle(&store, &test_config()).await;
+        assert_eq!(old_keys, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_cycle_ignores_empty_and_unrelated_keys() {
+        let store = MockLogStore::new();
+        store.push("logs:user_1:100", "entry").await.unwrap();
+        store.ltrim("logs:user_1:100", 1, -1).await.unwrap();  // Drains it back to empty
+        store.push("other:key", "unrelated").await.unwrap();
+
+        let old_keys = run_cleanup_cycle(&store, &test_config()).await;
+        assert_eq!(old_keys, 0);
+    }
+}
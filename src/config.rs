@@ -9,6 +9,7 @@ pub struct Config {
     pub server: ServerConfig,
     pub redis: RedisConfig,
     pub drainer: DrainerConfig,
+    pub cleanup: CleanupConfig,
 }
 
 /// Server configuration
@@ -32,6 +33,10 @@ pub struct RedisConfig {
     pub url: String,
     pub key_expiration_seconds: Option<u64>,  // How long keys stay in Redis before expiring (None = disabled)
     pub disable_ttl: bool,  // If true, don't set TTL (rely on drainer DELETE only)
+    pub pool_max_size: usize,  // Maximum number of pooled connections
+    pub pool_wait_timeout_seconds: Option<u64>,  // How long to wait for a free connection (None = wait forever)
+    pub pool_create_timeout_seconds: Option<u64>,  // How long to wait when establishing a brand new connection
+    pub pool_recycle_timeout_seconds: Option<u64>,  // How long to wait when recycling a returned connection
 }
 
 /// Drainer configuration
@@ -41,6 +46,16 @@ pub struct DrainerConfig {
     pub log_pattern: String,     // Redis key pattern to match (e.g., "logs:user_*:*")
     pub max_retries: u32,        // Maximum retries for a failed key before giving up
     pub retry_delay_seconds: u64, // Delay between retries for failed keys
+    pub batch_size: usize,       // Max entries read/trimmed from a key per LRANGE/LTRIM round
+    pub lock_ttl_seconds: u64,   // Distributed lock TTL per key - must comfortably exceed drain time
+    pub lock_renew_interval_seconds: u64,  // How often to renew a held lock while draining a large key
+    pub scan_count: usize,       // SCAN COUNT hint - page size used to iterate the keyspace
+}
+
+/// Cleanup service configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupConfig {
+    pub scan_count: usize,  // SCAN COUNT hint - page size used to iterate the keyspace
 }
 
 impl Config {
@@ -91,12 +106,23 @@ impl Config {
                 // Drainer handles normal cleanup (every 30s), TTL catches edge cases
                 key_expiration_seconds: Some(86400),  // 24 hours - safety net
                 disable_ttl: false,  // Keep TTL enabled as safety net
+                pool_max_size: 16,  // 16 pooled connections is plenty for a single instance
+                pool_wait_timeout_seconds: Some(5),  // Fail fast rather than queue forever
+                pool_create_timeout_seconds: Some(5),  // Fail fast if Redis refuses new connections
+                pool_recycle_timeout_seconds: Some(5),  // Fail fast if a returned connection is unhealthy
             },
             drainer: DrainerConfig {
                 interval_seconds: 30,  // 30 seconds (more frequent to prevent data loss)
                 log_pattern: "logs:user_*:*".to_string(),
                 max_retries: 3,        // Retry failed keys 3 times
                 retry_delay_seconds: 30, // Wait 30 seconds between retries
+                batch_size: 500,        // Drain 500 entries at a time to bound memory use
+                lock_ttl_seconds: 60,   // Comfortably longer than a single key's drain time
+                lock_renew_interval_seconds: 20,  // Renew at roughly a third of the TTL
+                scan_count: 200,        // Page size for the SCAN sweep over the keyspace
+            },
+            cleanup: CleanupConfig {
+                scan_count: 500,  // Larger page than the drainer's since this sweep just checks LLEN
             },
         };
         
@@ -0,0 +1,44 @@
+// Single-instance Redis distributed lock primitives, shared by anything
+// that needs to coordinate mutually-exclusive access to a key across
+// multiple process instances (today: the drainer, so two replicas can't
+// double-process the same user's log key).
+//
+// Protocol: acquire with `SET lock:<key> <token> NX PX <ttl_ms>` - success
+// means we hold the lock until it expires. Renew periodically with a
+// check-and-reset Lua script if the held work might outlive the TTL.
+// Release with a check-and-delete Lua script, so we never delete a lock
+// that already expired and was re-acquired by someone else.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Only deletes the lock if it still holds our token, so an expired-and-
+/// reacquired lock is never torn down by its former holder.
+pub const RELEASE_LOCK_SCRIPT: &str = r#"
+if redis.call("get", KEYS[1]) == ARGV[1] then
+    return redis.call("del", KEYS[1])
+else
+    return 0
+end
+"#;
+
+/// Only resets the lock's TTL if it still holds our token, for the same
+/// reason `RELEASE_LOCK_SCRIPT` checks before deleting.
+pub const RENEW_LOCK_SCRIPT: &str = r#"
+if redis.call("get", KEYS[1]) == ARGV[1] then
+    return redis.call("pexpire", KEYS[1], ARGV[2])
+else
+    return 0
+end
+"#;
+
+static LOCK_TOKEN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Generate a token unique enough to safely identify "our" lock instance
+pub fn generate_lock_token() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let counter = LOCK_TOKEN_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{:x}", nanos, counter)
+}
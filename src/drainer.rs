@@ -1,10 +1,9 @@
 // Background drainer service - drains Redis cache to files
 use crate::config::DrainerConfig;
 use crate::file_redis_layer::get_log_file_path;
+use crate::log_store::LogStore;
 use crate::types::AppError;
-use redis::AsyncCommands;
 use std::collections::HashMap;
-use std::sync::Arc;
 use tokio::fs::OpenOptions;
 use tokio::io::AsyncWriteExt;
 use tokio::time::{interval, sleep, Duration};
@@ -40,158 +39,258 @@ impl RetryTracker {
     }
 }
 
-/// Drain a single Redis key (user's log cache) to file
-/// This reads all logs from Redis and writes them to the file system
-/// Returns Ok(()) on success, Err on failure (key is NOT deleted on failure)
-pub async fn drain_key_to_file(
-    redis_client: &redis::Client,
+/// Drain a single Redis key (user's log cache) to file, in fixed-size
+/// batches rather than reading the whole list into memory at once.
+///
+/// Each batch is read with `LRANGE key 0 N-1`, written to the file, and only
+/// then trimmed off with `LTRIM key N -1` (the store removes the key itself
+/// once the list becomes empty). Trimming only happens after a successful
+/// flush, so a crash mid-drain leaves the undrained suffix intact for the
+/// next cycle.
+///
+/// `buffer` is a caller-owned scratch buffer reused across batches (and
+/// across keys within a drain cycle) to avoid reallocating per call.
+/// Generic over `LogStore` so this can run against real Redis or the
+/// in-memory mock used in tests.
+pub async fn drain_key_to_file<S: LogStore>(
+    store: &S,
     key: &str,
+    batch_size: usize,
+    buffer: &mut Vec<String>,
 ) -> Result<(), AppError> {
-    let mut conn = redis_client
-        .get_async_connection()
-        .await
-        .map_err(|e| AppError::RedisError(e.to_string()))?;
-    
-    // Get all logs from Redis list (LRANGE 0 -1 = get all)
-    // We read ALL logs first to ensure atomicity
-    let logs: Vec<String> = conn
-        .lrange(key, 0, -1)
-        .await
-        .map_err(|e| AppError::RedisError(e.to_string()))?;
-    
-    if logs.is_empty() {
-        return Ok(());  // Nothing to drain
-    }
-    
     // Parse key to extract user_id and date
     // Format: logs:user_123:19847
     let parts: Vec<&str> = key.split(':').collect();
     if parts.len() != 3 {
-        return Err(AppError::RedisError("Invalid key format".to_string()));
+        return Err(AppError::ParseError("Invalid key format".to_string()));
     }
-    
+
     let user_id = parts[1].strip_prefix("user_").unwrap_or(parts[1]);
     let days = parts[2];
-    
+
     // Reconstruct timestamp from days (for get_log_file_path)
     let timestamp = days.parse::<u64>()
-        .map_err(|_| AppError::RedisError("Invalid days format".to_string()))?
+        .map_err(|_| AppError::ParseError("Invalid days format".to_string()))?
         * 86400;  // Convert days back to seconds
-    
+
     let file_path = get_log_file_path(user_id, timestamp);
-    
-    // Create directory if needed
-    if let Some(parent) = std::path::Path::new(&file_path).parent() {
-        tokio::fs::create_dir_all(parent)
+    let batch_stop = (batch_size.max(1) - 1) as isize;
+
+    // The file is opened lazily on the first non-empty batch, so an
+    // already-empty key doesn't touch the filesystem at all
+    let mut file: Option<tokio::fs::File> = None;
+    let mut total_drained = 0usize;
+
+    loop {
+        buffer.clear();
+        let batch = store.lrange(key, 0, batch_stop).await?;
+
+        if batch.is_empty() {
+            break;
+        }
+        buffer.extend(batch);
+
+        if file.is_none() {
+            if let Some(parent) = std::path::Path::new(&file_path).parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| AppError::FileError(format!("Could not create directory: {}", e)))?;
+            }
+            file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&file_path)
+                    .await
+                    .map_err(|e| AppError::FileError(format!("Could not open file {}: {}", file_path, e)))?,
+            );
+        }
+        let open_file = file.as_mut().unwrap();
+
+        for log_line in buffer.iter() {
+            open_file
+                .write_all(format!("{}\n", log_line).as_bytes())
+                .await
+                .map_err(|e| AppError::FileError(format!("Could not write to {}: {}", file_path, e)))?;
+        }
+
+        // Flush to ensure data is written to disk
+        open_file
+            .flush()
             .await
-            .map_err(|e| AppError::FileError(format!("Could not create directory: {}", e)))?;
+            .map_err(|e| AppError::FileError(format!("Could not flush file {}: {}", file_path, e)))?;
+
+        // Only trim the drained prefix AFTER a successful flush
+        store.ltrim(key, buffer.len() as isize, -1).await?;
+
+        total_drained += buffer.len();
     }
-    
-    // Open file in append mode
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&file_path)
-        .await
-        .map_err(|e| AppError::FileError(format!("Could not open file {}: {}", file_path, e)))?;
-    
-    // Write all logs in batch (much faster than one-by-one!)
-    // If this fails, Redis key is NOT deleted, so we can retry
-    for log_line in &logs {
-        file.write_all(format!("{}\n", log_line).as_bytes())
-            .await
-            .map_err(|e| AppError::FileError(format!("Could not write to {}: {}", file_path, e)))?;
+
+    if total_drained > 0 {
+        println!("✅ Drained {} logs from {} to {}", total_drained, key, file_path);
     }
-    
-    // Flush to ensure data is written to disk
-    file.flush()
-        .await
-        .map_err(|e| AppError::FileError(format!("Could not flush file {}: {}", file_path, e)))?;
-    
-    // Only delete the Redis key AFTER successful write
-    // This ensures atomicity: either all logs are written and key is deleted,
-    // or nothing happens and we can retry
-    conn.del::<_, ()>(key)
-        .await
-        .map_err(|e| AppError::RedisError(format!("Could not delete key {}: {}", key, e)))?;
-    
-    println!("‚úÖ Drained {} logs from {} to {}", logs.len(), key, file_path);
+
     Ok(())
 }
 
+/// Acquire the distributed lock for `key`, drain it if acquired, then
+/// release the lock. Returns `None` if the lock could not be acquired (held
+/// by another drainer instance, or a store error), meaning this cycle
+/// should just skip the key.
+///
+/// A large key can take longer to drain than the lock's TTL, so a
+/// background task renews the lock every `lock_renew_interval_seconds`
+/// while draining runs, and is stopped as soon as draining finishes either
+/// way.
+async fn drain_with_lock<S: LogStore>(
+    store: &S,
+    key: &str,
+    batch_size: usize,
+    buffer: &mut Vec<String>,
+    lock_ttl_seconds: u64,
+    lock_renew_interval_seconds: u64,
+) -> Option<Result<(), AppError>> {
+    let lock_key = format!("lock:{}", key);
+    let ttl_ms = lock_ttl_seconds * 1000;
+
+    match store.try_acquire_lock(&lock_key, ttl_ms).await {
+        Ok(Some(token)) => {
+            let result = {
+                let lock_key = lock_key.clone();
+                let token = token.clone();
+                let renew_ttl_ms = ttl_ms;
+                let renew_interval = Duration::from_secs(lock_renew_interval_seconds);
+                let renewal = async {
+                    let mut renew_timer = interval(renew_interval);
+                    renew_timer.tick().await;  // First tick fires immediately; skip it
+                    loop {
+                        renew_timer.tick().await;
+                        if let Err(e) = store.renew_lock(&lock_key, &token, renew_ttl_ms).await {
+                            eprintln!("⚠️  Drainer: Failed to renew lock for {}: {:?}", lock_key, e);
+                        }
+                    }
+                };
+                tokio::select! {
+                    result = drain_key_to_file(store, key, batch_size, buffer) => result,
+                    _ = renewal => unreachable!("renewal loop never returns"),
+                }
+            };
+            if let Err(e) = store.release_lock(&lock_key, &token).await {
+                eprintln!("⚠️  Drainer: Failed to release lock for {}: {:?}", key, e);
+            }
+            Some(result)
+        }
+        Ok(None) => {
+            println!("⏭️  Drainer: Skipping {} this cycle (lock held by another instance)", key);
+            None
+        }
+        Err(e) => {
+            eprintln!("❌ Drainer: Failed to acquire lock for {}: {:?}", key, e);
+            None
+        }
+    }
+}
+
 /// Background drainer task - runs periodically
-/// Finds all Redis keys matching our log pattern and drains them to files
-/// 
+/// Finds all keys matching our log pattern and drains them to files
+///
 /// Features:
 /// - Retry mechanism for failed keys
 /// - Tracks retry attempts
 /// - Handles partial failures gracefully
 /// - Logs metrics about success/failure rates
-pub async fn start_drainer(
-    redis_client: Arc<redis::Client>,
-    config: DrainerConfig,
-) {
+///
+/// Generic over `LogStore` so the same loop (and its retry/lock behavior)
+/// can be driven by the in-memory mock in tests.
+pub async fn start_drainer<S: LogStore>(store: S, config: DrainerConfig) {
     println!(
-        "üîÑ Starting background drainer (runs every {} seconds, pattern: {})",
+        "🔄 Starting background drainer (runs every {} seconds, pattern: {})",
         config.interval_seconds,
         config.log_pattern
     );
     println!("   Max retries: {}, Retry delay: {}s", config.max_retries, config.retry_delay_seconds);
-    
+
     let mut interval_timer = interval(Duration::from_secs(config.interval_seconds));
     let mut retry_tracker = RetryTracker::new();
-    
+
     loop {
         interval_timer.tick().await;  // Wait for next interval
-        
-        println!("üîÑ Drainer: Starting batch drain cycle...");
-        
-        // Get Redis connection
-        let mut conn = match redis_client.get_async_connection().await {
-            Ok(conn) => conn,
-            Err(e) => {
-                eprintln!("‚ùå Drainer: Failed to get Redis connection: {}", e);
-                continue;
-            }
-        };
-        
-        // Find all keys matching our log pattern
-        // Note: KEYS blocks Redis, but for learning it's fine
-        // In production, use SCAN with cursor for non-blocking iteration
-        let keys: Vec<String> = match conn.keys(&config.log_pattern).await {
-            Ok(keys) => keys,
+        run_drain_cycle(&store, &config, &mut retry_tracker).await;
+    }
+}
+
+/// A single drain cycle: find matching keys, drain each under its lock, then
+/// give failed keys one delayed retry. Split out from `start_drainer` so it
+/// can be driven directly (and repeatedly, without waiting on the interval
+/// timer) from tests.
+async fn run_drain_cycle<S: LogStore>(
+    store: &S,
+    config: &DrainerConfig,
+    retry_tracker: &mut RetryTracker,
+) {
+    println!("🔄 Drainer: Starting batch drain cycle...");
+
+    let mut total_drained = 0;
+    let mut total_failed = 0;
+    let mut retried_keys = 0;
+
+    // Reused across every key (and every batch within a key) this cycle,
+    // instead of allocating a fresh Vec per call
+    let mut buffer: Vec<String> = Vec::with_capacity(config.batch_size);
+
+    // Sweep the keyspace page by page with SCAN instead of blocking on a
+    // single KEYS call, draining each page as it arrives so peak key-list
+    // memory stays bounded by `scan_count` rather than the whole matching
+    // set. A key that disappears between being listed and being drained
+    // (TTL expiry, another drainer instance) just drains to zero lines,
+    // which `drain_key_to_file` already treats as a no-op success.
+    let mut cursor: u64 = 0;
+    loop {
+        let page = match store.scan(cursor, &config.log_pattern, config.scan_count).await {
+            Ok(page) => page,
             Err(e) => {
-                eprintln!("‚ùå Drainer: Failed to get keys: {}", e);
-                continue;
+                eprintln!("❌ Drainer: Failed to scan keys: {:?}", e);
+                break;
             }
         };
-        
-        let mut total_drained = 0;
-        let mut total_failed = 0;
-        let mut retried_keys = 0;
-        
-        // Drain each key
+        let (next_cursor, keys) = page;
+
+        // Drain each key. Each key is only drained while we hold its
+        // distributed lock, so a second drainer instance running
+        // concurrently can't double-process it.
         for key in keys {
-            match drain_key_to_file(&redis_client, &key).await {
-                Ok(_) => {
+            let drain_result =
+                drain_with_lock(
+                    store,
+                    &key,
+                    config.batch_size,
+                    &mut buffer,
+                    config.lock_ttl_seconds,
+                    config.lock_renew_interval_seconds,
+                )
+                .await;
+
+            match drain_result {
+                None => continue,  // Lock held elsewhere (or acquire failed) - skip this cycle
+                Some(Ok(_)) => {
                     // Success! Reset retry counter for this key
                     retry_tracker.reset(&key);
                     total_drained += 1;
                 }
-                Err(e) => {
+                Some(Err(e)) => {
                     total_failed += 1;
                     let retry_count = retry_tracker.increment(&key);
-                    
+
                     if retry_tracker.should_retry(&key, config.max_retries) {
                         eprintln!(
-                            "‚ö†Ô∏è  Drainer: Failed to drain {} (attempt {}/{}): {:?}",
+                            "⚠️  Drainer: Failed to drain {} (attempt {}/{}): {:?}",
                             key, retry_count, config.max_retries, e
                         );
                         retried_keys += 1;
                     } else {
                         // Max retries exceeded - log as dead letter
                         eprintln!(
-                            "‚ùå Drainer: Key {} exceeded max retries ({}). Moving to dead letter handling.",
+                            "❌ Drainer: Key {} exceeded max retries ({}). Moving to dead letter handling.",
                             key, config.max_retries
                         );
                         // TODO: Move to dead letter queue or alert
@@ -204,40 +303,57 @@ pub async fn start_drainer(
                 }
             }
         }
-        
-        // Print summary
+
+        cursor = next_cursor;
+        if cursor == 0 {
+            break;  // SCAN signals a full pass over the keyspace with cursor 0
+        }
+    }
+
+    // Print summary
+    println!(
+        "✅ Drainer: Completed cycle. Drained: {}, Failed: {}, Retrying: {}",
+        total_drained, total_failed, retried_keys
+    );
+
+    // If there are failed keys that should be retried, wait and retry them
+    if retried_keys > 0 {
         println!(
-            "‚úÖ Drainer: Completed cycle. Drained: {}, Failed: {}, Retrying: {}",
-            total_drained, total_failed, retried_keys
+            "⏳ Waiting {} seconds before retrying {} failed keys...",
+            config.retry_delay_seconds, retried_keys
         );
-        
-        // If there are failed keys that should be retried, wait and retry them
-        if retried_keys > 0 {
-            println!(
-                "‚è≥ Waiting {} seconds before retrying {} failed keys...",
-                config.retry_delay_seconds, retried_keys
-            );
-            sleep(Duration::from_secs(config.retry_delay_seconds)).await;
-            
-            // Retry failed keys
-            let failed_keys = retry_tracker.get_failed_keys();
-            for key in failed_keys {
-                if !retry_tracker.should_retry(&key, config.max_retries) {
-                    continue;  // Skip keys that exceeded max retries
+        sleep(Duration::from_secs(config.retry_delay_seconds)).await;
+
+        // Retry failed keys
+        let failed_keys = retry_tracker.get_failed_keys();
+        for key in failed_keys {
+            if !retry_tracker.should_retry(&key, config.max_retries) {
+                continue;  // Skip keys that exceeded max retries
+            }
+
+            let retry_result =
+                drain_with_lock(
+                    store,
+                    &key,
+                    config.batch_size,
+                    &mut buffer,
+                    config.lock_ttl_seconds,
+                    config.lock_renew_interval_seconds,
+                )
+                .await;
+
+            match retry_result {
+                None => continue,  // Lock held elsewhere - try again next cycle
+                Some(Ok(_)) => {
+                    retry_tracker.reset(&key);
+                    println!("✅ Retry successful for {}", key);
                 }
-                
-                match drain_key_to_file(&redis_client, &key).await {
-                    Ok(_) => {
-                        retry_tracker.reset(&key);
-                        println!("‚úÖ Retry successful for {}", key);
-                    }
-                    Err(e) => {
-                        let retry_count = retry_tracker.increment(&key);
-                        eprintln!(
-                            "‚ö†Ô∏è  Retry failed for {} (attempt {}/{}): {:?}",
-                            key, retry_count, config.max_retries, e
-                        );
-                    }
+                Some(Err(e)) => {
+                    let retry_count = retry_tracker.increment(&key);
+                    eprintln!(
+                        "⚠️  Retry failed for {} (attempt {}/{}): {:?}",
+                        key, retry_count, config.max_retries, e
+                    );
                 }
             }
         }
@@ -247,7 +363,7 @@ pub async fn start_drainer(
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+
     #[test]
     fn test_drain_key_parsing() {
         // Test key parsing logic
@@ -257,11 +373,11 @@ mod tests {
         assert_eq!(parts[0], "logs");
         assert_eq!(parts[1], "user_123");
         assert_eq!(parts[2], "19847");
-        
+
         let user_id = parts[1].strip_prefix("user_").unwrap_or(parts[1]);
         assert_eq!(user_id, "123");
     }
-    
+
     #[test]
     fn test_invalid_key_format() {
         let invalid_keys = vec![
@@ -269,35 +385,127 @@ mod tests {
             "logs:user_123:19847:extra", // Too many parts
             "invalid",                 // Wrong format
         ];
-        
+
         for key in invalid_keys {
             let parts: Vec<&str> = key.split(':').collect();
             assert_ne!(parts.len(), 3, "Key {} should be invalid", key);
         }
     }
-    
+
     #[test]
     fn test_retry_tracker() {
         let mut tracker = RetryTracker::new();
         let key = "logs:user_123:19847";
-        
+
         // First attempt
         assert!(tracker.should_retry(key, 3));
         assert_eq!(tracker.increment(key), 1);
-        
+
         // Second attempt
         assert!(tracker.should_retry(key, 3));
         assert_eq!(tracker.increment(key), 2);
-        
+
         // Third attempt
         assert!(tracker.should_retry(key, 3));
         assert_eq!(tracker.increment(key), 3);
-        
+
         // Fourth attempt - should not retry
         assert!(!tracker.should_retry(key, 3));
-        
+
         // Reset
         tracker.reset(key);
         assert!(tracker.should_retry(key, 3));
     }
+
+    // Serializes tests below that change the process's current directory -
+    // drain_key_to_file writes relative to `logs/...`, so each test needs
+    // its own working directory without racing the others.
+    static CWD_GUARD: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[tokio::test]
+    async fn test_drain_pipeline_writes_and_clears_key_on_success() {
+        use crate::log_store::MockLogStore;
+
+        let _guard = CWD_GUARD.lock().unwrap();
+        let tmp_dir = std::env::temp_dir().join("rustlogger-test-drain-success");
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        std::env::set_current_dir(&tmp_dir).unwrap();
+
+        let store = MockLogStore::new();
+        let key = "logs:user_42:19000";
+        store.push(key, r#"{"event":"one"}"#).await.unwrap();
+        store.push(key, r#"{"event":"two"}"#).await.unwrap();
+
+        let mut buffer = Vec::new();
+        drain_key_to_file(&store, key, 500, &mut buffer).await.unwrap();
+
+        // The key should be fully drained (and therefore gone) from the store
+        let remaining = store.lrange(key, 0, -1).await.unwrap();
+        assert!(remaining.is_empty());
+
+        let contents = std::fs::read_to_string("logs/user_42/19000.jsonl").unwrap();
+        assert!(contents.contains("one"));
+        assert!(contents.contains("two"));
+
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_drain_cycle_drains_matching_keys_and_leaves_no_failed_keys() {
+        use crate::config::DrainerConfig;
+        use crate::log_store::MockLogStore;
+
+        let _guard = CWD_GUARD.lock().unwrap();
+        let tmp_dir = std::env::temp_dir().join("rustlogger-test-drain-retry");
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        std::env::set_current_dir(&tmp_dir).unwrap();
+
+        let store = MockLogStore::new();
+        store.push("logs:user_7:19001", "line").await.unwrap();
+
+        let config = DrainerConfig {
+            interval_seconds: 9999,
+            log_pattern: "logs:*:*".to_string(),
+            max_retries: 3,
+            retry_delay_seconds: 0,
+            batch_size: 10,
+            lock_ttl_seconds: 30,
+            lock_renew_interval_seconds: 10,
+            scan_count: 200,
+        };
+        let mut retry_tracker = RetryTracker::new();
+
+        run_drain_cycle(&store, &config, &mut retry_tracker).await;
+
+        assert!(retry_tracker.get_failed_keys().is_empty());
+        let remaining = store.keys("logs:*:*").await.unwrap();
+        assert!(remaining.is_empty());
+
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_drain_with_lock_skips_key_when_lock_already_held() {
+        use crate::log_store::MockLogStore;
+
+        let store = MockLogStore::new();
+        let key = "logs:user_9:19002";
+        store.push(key, "line").await.unwrap();
+
+        // Simulate another drainer instance already holding this key's lock
+        let lock_key = format!("lock:{}", key);
+        let held_token = store.try_acquire_lock(&lock_key, 30_000).await.unwrap().unwrap();
+
+        let mut buffer = Vec::new();
+        let result = drain_with_lock(&store, key, 500, &mut buffer, 30, 10).await;
+        assert!(result.is_none(), "drain_with_lock should skip a key whose lock is held elsewhere");
+
+        // The key is untouched since the drain never ran
+        let remaining = store.lrange(key, 0, -1).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+
+        store.release_lock(&lock_key, &held_token).await.unwrap();
+    }
 }
@@ -1,6 +1,60 @@
 // Redis cache layer - handles all Redis operations
+use crate::config::RedisConfig;
+use crate::log_store::LogStore;
 use crate::types::{AppError, LogEvent};
-use redis::AsyncCommands;
+use deadpool_redis::{Config as PoolConfig, PoolConfig as DeadpoolPoolConfig, Runtime, Timeouts};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Build a deadpool-redis connection pool from the Redis configuration.
+/// Shared by the API server, the drainer, and the cleanup service, so all
+/// three bound their Redis connection count through the same pool type
+/// instead of opening a fresh connection per call.
+pub fn create_redis_pool(config: &RedisConfig) -> deadpool_redis::Pool {
+    let mut pool_config = PoolConfig::from_url(config.url.clone());
+
+    let mut inner_pool_config = DeadpoolPoolConfig::new(config.pool_max_size);
+    inner_pool_config.timeouts = Timeouts {
+        wait: config.pool_wait_timeout_seconds.map(Duration::from_secs),
+        create: config.pool_create_timeout_seconds.map(Duration::from_secs),
+        recycle: config.pool_recycle_timeout_seconds.map(Duration::from_secs),
+    };
+    pool_config.pool = Some(inner_pool_config);
+
+    pool_config
+        .create_pool(Some(Runtime::Tokio1))
+        .expect("Failed to create Redis connection pool")
+}
+
+/// Extra attempts allowed for a retryable write, beyond the first try
+const MAX_WRITE_RETRIES: u32 = 3;
+/// Base delay for the exponential backoff between write retries
+const RETRY_BASE_DELAY_MS: u64 = 50;
+
+/// Run `op` up to `MAX_WRITE_RETRIES` extra times with exponential backoff,
+/// but only while the error it returns is `retryable()` - a permanent
+/// command error (bad type, bad arguments) fails immediately instead of
+/// wasting retries on it. Lets ingest ride out a brief Redis blip (a
+/// dropped connection, a momentary timeout) instead of returning a 500 on
+/// the first hiccup.
+async fn retry_with_backoff<F, Fut>(mut op: F) -> Result<(), AppError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<(), AppError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < MAX_WRITE_RETRIES && e.retryable() => {
+                attempt += 1;
+                let delay_ms = RETRY_BASE_DELAY_MS * 2u64.pow(attempt - 1);
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
 
 /// Get Redis key for a user's log cache
 /// Format: logs:user_{user_id}:{days_since_epoch}
@@ -16,68 +70,90 @@ pub fn get_log_file_path(user_id: &str, timestamp: u64) -> String {
     format!("logs/user_{}/{}.jsonl", user_id, days_since_epoch)
 }
 
-/// Write log event to Redis cache
-/// This is FAST - Redis is in-memory, so writes are instant
-pub async fn write_to_cache(
-    redis_client: &redis::Client,
+/// Write log event to cache
+/// This is FAST - the store is in-memory, so writes are instant.
+/// Generic over `LogStore` so it can run against real Redis or the
+/// in-memory mock used in tests.
+pub async fn write_to_cache<S: LogStore>(
+    store: &S,
     event: &LogEvent,
     expiration_seconds: Option<u64>,
     disable_ttl: bool,
 ) -> Result<(), AppError> {
-    // Get Redis connection from pool
-    let mut conn = redis_client
-        .get_async_connection()
-        .await
-        .map_err(|e| AppError::RedisError(e.to_string()))?;
-    
-    // Get the Redis key for this user's log cache
+    // Get the cache key for this user's log cache
     let key = get_redis_key(&event.user_id, event.timestamp);
-    
+
     // Serialize event to JSON
     let json_line = serde_json::to_string(event)
         .map_err(|e| AppError::SerializationError(e.to_string()))?;
-    
-    // Append to Redis list (RPUSH = append to end of list)
+
+    // Append to the log list (RPUSH = append to end of list)
     // Redis lists are perfect for log streams!
-    conn.rpush::<_, _, ()>(&key, &json_line)
-        .await
-        .map_err(|e| AppError::RedisError(e.to_string()))?;
-    
+    retry_with_backoff(|| store.push(&key, &json_line)).await?;
+
     // Set expiration on the key (only if TTL is enabled)
     if !disable_ttl {
         if let Some(ttl) = expiration_seconds {
-            conn.expire::<_, ()>(&key, ttl as i64)
-                .await
-                .map_err(|e| AppError::RedisError(e.to_string()))?;
+            retry_with_backoff(|| store.expire(&key, ttl)).await?;
         }
     }
     // If disable_ttl is true, we rely on drainer DELETE only (safest)
-    
+
     Ok(())
 }
 
-/// Read logs from Redis cache for a specific user
+/// Write a batch of log events to cache with one pipelined round trip per
+/// Redis key instead of one RPUSH/EXPIRE pair per event; a batch that spans
+/// several keys takes several round trips. Events are grouped by their
+/// computed Redis key first, since events for the same user/day land on the
+/// same key and can share a single EXPIRE. Returns the number of entries written per key
+/// so callers can confirm ingestion - a key that `push_batch` reports as
+/// failed is left out of the map entirely rather than reported as written,
+/// so a caller retrying on a partial failure doesn't skip a key that never
+/// actually made it to Redis.
+pub async fn write_batch_to_cache<S: LogStore>(
+    store: &S,
+    events: &[LogEvent],
+    expiration_seconds: Option<u64>,
+    disable_ttl: bool,
+) -> Result<HashMap<String, usize>, AppError> {
+    let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+
+    for event in events {
+        let key = get_redis_key(&event.user_id, event.timestamp);
+        let json_line = serde_json::to_string(event)
+            .map_err(|e| AppError::SerializationError(e.to_string()))?;
+        grouped.entry(key).or_default().push(json_line);
+    }
+
+    let ttl = if disable_ttl { None } else { expiration_seconds };
+    let failures = store.push_batch(&grouped, ttl).await?;
+
+    for (key, err) in &failures {
+        eprintln!("⚠️  write_batch_to_cache: key {} failed to write: {}", key, err);
+    }
+    let failed_keys: std::collections::HashSet<&String> =
+        failures.iter().map(|(key, _)| key).collect();
+
+    Ok(grouped
+        .into_iter()
+        .filter(|(key, _)| !failed_keys.contains(key))
+        .map(|(key, values)| (key, values.len()))
+        .collect())
+}
+
+/// Read logs from cache for a specific user
 /// Returns all logs in the cache for that user's key
 #[allow(dead_code)]  // Reserved for future use (e.g., stats endpoint)
-pub async fn read_from_cache(
-    redis_client: &redis::Client,
+pub async fn read_from_cache<S: LogStore>(
+    store: &S,
     user_id: &str,
     timestamp: u64,
 ) -> Result<Vec<String>, AppError> {
-    let mut conn = redis_client
-        .get_async_connection()
-        .await
-        .map_err(|e| AppError::RedisError(e.to_string()))?;
-    
     let key = get_redis_key(user_id, timestamp);
-    
-    // Get all logs from Redis list (LRANGE 0 -1 = get all)
-    let logs: Vec<String> = conn
-        .lrange(&key, 0, -1)
-        .await
-        .map_err(|e| AppError::RedisError(e.to_string()))?;
-    
-    Ok(logs)
+
+    // Get all logs from the list (LRANGE 0 -1 = get all)
+    store.lrange(&key, 0, -1).await
 }
 
 #[cfg(test)]
@@ -119,4 +195,97 @@ mod tests {
         
         assert_eq!(key_days[2], path_days[2].strip_suffix(".jsonl").unwrap());
     }
+
+    #[tokio::test]
+    async fn test_write_to_cache_retries_past_transient_failures() {
+        use crate::log_store::MockLogStore;
+        use crate::types::LogEvent;
+
+        let store = MockLogStore::new();
+        store.fail_next_pushes(MAX_WRITE_RETRIES);  // Exactly enough retries to still succeed
+
+        let event = LogEvent {
+            user_id: "1".to_string(),
+            event: "login".to_string(),
+            timestamp: 1712345678,
+        };
+
+        write_to_cache(&store, &event, Some(60), false).await.unwrap();
+
+        let stored = read_from_cache(&store, "1", 1712345678).await.unwrap();
+        assert_eq!(stored.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_write_to_cache_gives_up_after_max_retries() {
+        use crate::log_store::MockLogStore;
+        use crate::types::LogEvent;
+
+        let store = MockLogStore::new();
+        store.fail_next_pushes(MAX_WRITE_RETRIES + 1);  // One more failure than the retry budget allows
+
+        let event = LogEvent {
+            user_id: "2".to_string(),
+            event: "login".to_string(),
+            timestamp: 1712345678,
+        };
+
+        let result = write_to_cache(&store, &event, Some(60), false).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_batch_to_cache_groups_by_key_and_counts_per_key() {
+        use crate::log_store::MockLogStore;
+        use crate::types::LogEvent;
+
+        let store = MockLogStore::new();
+        let events = vec![
+            LogEvent { user_id: "1".to_string(), event: "login".to_string(), timestamp: 1712345678 },
+            LogEvent { user_id: "1".to_string(), event: "click".to_string(), timestamp: 1712345679 },
+            LogEvent { user_id: "2".to_string(), event: "login".to_string(), timestamp: 1712345678 },
+        ];
+
+        let counts = write_batch_to_cache(&store, &events, Some(60), false).await.unwrap();
+
+        let key_1 = get_redis_key("1", 1712345678);
+        let key_2 = get_redis_key("2", 1712345678);
+        assert_eq!(counts.get(&key_1), Some(&2));
+        assert_eq!(counts.get(&key_2), Some(&1));
+
+        let stored_1 = read_from_cache(&store, "1", 1712345678).await.unwrap();
+        assert_eq!(stored_1.len(), 2);
+        let stored_2 = read_from_cache(&store, "2", 1712345678).await.unwrap();
+        assert_eq!(stored_2.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_write_batch_to_cache_excludes_failed_key_from_counts() {
+        use crate::log_store::MockLogStore;
+        use crate::types::LogEvent;
+
+        let store = MockLogStore::new();
+        // Exactly one key's single push fails - the other key's push still
+        // succeeds. Which of the two keys fails is unspecified (HashMap
+        // iteration order isn't guaranteed), so the assertions below only
+        // rely on the resulting shape: one key reported, one key dropped.
+        store.fail_next_pushes(1);
+
+        let events = vec![
+            LogEvent { user_id: "1".to_string(), event: "login".to_string(), timestamp: 1712345678 },
+            LogEvent { user_id: "2".to_string(), event: "login".to_string(), timestamp: 1712345678 },
+        ];
+
+        let counts = write_batch_to_cache(&store, &events, Some(60), false).await.unwrap();
+
+        assert_eq!(counts.len(), 1);
+
+        let key_1 = get_redis_key("1", 1712345678);
+        let key_2 = get_redis_key("2", 1712345678);
+        let succeeded_key = if counts.contains_key(&key_1) { &key_1 } else { &key_2 };
+        let failed_key = if succeeded_key == &key_1 { &key_2 } else { &key_1 };
+
+        assert_eq!(counts.get(succeeded_key), Some(&1));
+        assert_eq!(counts.get(failed_key), None);
+    }
 }
@@ -0,0 +1,10 @@
+// Library crate - exposes the shared modules used by the API server,
+// the standalone drainer binary, and the integration tests.
+pub mod types;
+pub mod cleanup;
+pub mod config;
+pub mod dlock;
+pub mod file_redis_layer;
+pub mod drainer;
+pub mod log_store;
+pub mod rate_limit;
@@ -0,0 +1,698 @@
+// Abstraction over the Redis operations the log pipeline relies on, so the
+// ingest path and the drainer can run against either real Redis or an
+// in-memory mock - the latter lets the full pipeline be exercised in tests
+// without a live Redis server.
+use crate::dlock::{generate_lock_token, RELEASE_LOCK_SCRIPT, RENEW_LOCK_SCRIPT};
+use crate::types::AppError;
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as TokioMutex;
+
+/// The subset of Redis list/key operations the log pipeline actually uses
+#[async_trait]
+pub trait LogStore: Send + Sync {
+    async fn push(&self, key: &str, value: &str) -> Result<(), AppError>;
+    async fn expire(&self, key: &str, ttl_seconds: u64) -> Result<(), AppError>;
+
+    /// Push every value onto its key (and, if `ttl_seconds` is set, refresh
+    /// that key's expiration), batching each key's commands into one round
+    /// trip where the backend supports it. Each key is handled
+    /// independently, so one key erroring (e.g. a real Redis `WRONGTYPE`
+    /// because that key already holds a non-list value) doesn't take the
+    /// rest of the batch down with it - the returned `Vec` lists which keys
+    /// failed and why, letting the caller tell already-stored events apart
+    /// from lost ones instead of getting back one opaque error for the
+    /// whole batch.
+    ///
+    /// Default implementation just loops over `push`/`expire` - correct for
+    /// backends (like the in-memory mock) with no round-trip cost to save;
+    /// `RedisLogStore` overrides this with a real `redis::pipe()` batch per
+    /// key.
+    async fn push_batch(
+        &self,
+        entries: &HashMap<String, Vec<String>>,
+        ttl_seconds: Option<u64>,
+    ) -> Result<Vec<(String, AppError)>, AppError> {
+        let mut failures = Vec::new();
+
+        for (key, values) in entries {
+            let mut key_failed = false;
+            for value in values {
+                if let Err(e) = self.push(key, value).await {
+                    failures.push((key.clone(), e));
+                    key_failed = true;
+                    break;
+                }
+            }
+            if !key_failed {
+                if let Some(ttl) = ttl_seconds {
+                    if let Err(e) = self.expire(key, ttl).await {
+                        failures.push((key.clone(), e));
+                    }
+                }
+            }
+        }
+
+        Ok(failures)
+    }
+
+    async fn lrange(&self, key: &str, start: isize, stop: isize) -> Result<Vec<String>, AppError>;
+    async fn ltrim(&self, key: &str, start: isize, stop: isize) -> Result<(), AppError>;
+    async fn del(&self, key: &str) -> Result<(), AppError>;
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>, AppError>;
+    async fn llen(&self, key: &str) -> Result<usize, AppError>;
+
+    /// Iterate the keyspace one page at a time instead of blocking on a
+    /// full `KEYS` scan. `cursor` starts at `0`; keep calling with the
+    /// returned cursor until it comes back as `0` again, which signals the
+    /// sweep is complete. Default implementation falls back to `keys()` in
+    /// a single page - fine for backends (like the in-memory mock) that
+    /// have no blocking concern in the first place; `RedisLogStore`
+    /// overrides this with a real `SCAN` cursor.
+    async fn scan(&self, _cursor: u64, pattern: &str, _count: usize) -> Result<(u64, Vec<String>), AppError> {
+        Ok((0, self.keys(pattern).await?))
+    }
+
+    /// Try to acquire a short-lived per-key lock before draining it.
+    /// Defaults to always succeeding - single-instance backends and test
+    /// doubles don't need real locking. `RedisLogStore` overrides this with
+    /// a real `SET NX PX` / Lua-script release pair so multiple drainer
+    /// replicas can't double-process the same key.
+    async fn try_acquire_lock(&self, _lock_key: &str, _ttl_ms: u64) -> Result<Option<String>, AppError> {
+        Ok(Some(String::new()))
+    }
+
+    async fn release_lock(&self, _lock_key: &str, _token: &str) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    /// Reset a held lock's TTL so work that outlives the original TTL
+    /// doesn't lose the lock partway through. Defaults to a no-op, matching
+    /// `try_acquire_lock`'s always-succeed default. `RedisLogStore` overrides
+    /// this with a real check-and-reset Lua script.
+    async fn renew_lock(&self, _lock_key: &str, _token: &str, _ttl_ms: u64) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+/// Real Redis-backed implementation, built on a deadpool-redis pool
+#[derive(Clone)]
+pub struct RedisLogStore {
+    pool: deadpool_redis::Pool,
+    // A single connection opportunistically reused across calls that run
+    // in sequence - as a drain cycle's lock-acquire/lrange/ltrim/lock-release
+    // calls for one key do - instead of checking a fresh connection out of
+    // the pool for every command. See `conn`/`release` below.
+    warm_conn: Arc<TokioMutex<Option<deadpool_redis::Connection>>>,
+}
+
+impl RedisLogStore {
+    pub fn new(pool: deadpool_redis::Pool) -> Self {
+        Self {
+            pool,
+            warm_conn: Arc::new(TokioMutex::new(None)),
+        }
+    }
+
+    /// Check out a connection to run one command: take the warm slot left
+    /// behind by this store's previous call if nothing else has claimed it
+    /// yet, otherwise check a fresh connection out of the pool. Paired with
+    /// `release` below, this amortizes a drain cycle's sequential per-key
+    /// calls down to close to one pool checkout instead of one per command
+    /// - falling back to an extra checkout whenever a concurrent caller
+    /// (e.g. the lock-renewal task racing the main drain loop) beats us to
+    /// the slot.
+    async fn conn(&self) -> Result<deadpool_redis::Connection, AppError> {
+        if let Some(conn) = self.warm_conn.lock().await.take() {
+            return Ok(conn);
+        }
+        self.pool.get().await.map_err(AppError::PoolError)
+    }
+
+    /// Hand a still-healthy connection back to the warm slot for the next
+    /// call on this store to reuse. Only called on the success path - a
+    /// connection behind a failed command is left to drop straight back to
+    /// the pool instead, so a bad connection can't get stuck cycling
+    /// through the warm slot forever.
+    async fn release(&self, conn: deadpool_redis::Connection) {
+        *self.warm_conn.lock().await = Some(conn);
+    }
+}
+
+#[async_trait]
+impl LogStore for RedisLogStore {
+    async fn push(&self, key: &str, value: &str) -> Result<(), AppError> {
+        let mut conn = self.conn().await?;
+        match conn.rpush::<_, _, ()>(key, value).await {
+            Ok(()) => {
+                self.release(conn).await;
+                Ok(())
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn expire(&self, key: &str, ttl_seconds: u64) -> Result<(), AppError> {
+        let mut conn = self.conn().await?;
+        match conn.expire::<_, ()>(key, ttl_seconds as i64).await {
+            Ok(()) => {
+                self.release(conn).await;
+                Ok(())
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn lrange(&self, key: &str, start: isize, stop: isize) -> Result<Vec<String>, AppError> {
+        let mut conn = self.conn().await?;
+        match conn.lrange(key, start, stop).await {
+            Ok(values) => {
+                self.release(conn).await;
+                Ok(values)
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn ltrim(&self, key: &str, start: isize, stop: isize) -> Result<(), AppError> {
+        let mut conn = self.conn().await?;
+        match conn.ltrim::<_, ()>(key, start, stop).await {
+            Ok(()) => {
+                self.release(conn).await;
+                Ok(())
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn del(&self, key: &str) -> Result<(), AppError> {
+        let mut conn = self.conn().await?;
+        match conn.del::<_, ()>(key).await {
+            Ok(()) => {
+                self.release(conn).await;
+                Ok(())
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>, AppError> {
+        let mut conn = self.conn().await?;
+        match conn.keys(pattern).await {
+            Ok(values) => {
+                self.release(conn).await;
+                Ok(values)
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn llen(&self, key: &str) -> Result<usize, AppError> {
+        let mut conn = self.conn().await?;
+        match conn.llen(key).await {
+            Ok(len) => {
+                self.release(conn).await;
+                Ok(len)
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn push_batch(
+        &self,
+        entries: &HashMap<String, Vec<String>>,
+        ttl_seconds: Option<u64>,
+    ) -> Result<Vec<(String, AppError)>, AppError> {
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // One pipeline per key rather than one pipeline spanning the whole
+        // batch - keeps a single key's failure (e.g. a real Redis
+        // `WRONGTYPE` because that key already holds a non-list value) from
+        // taking every other key in the batch down with it. Still amortizes
+        // to roughly one connection checkout for the whole call via the
+        // warm connection slot.
+        let mut failures = Vec::new();
+
+        for (key, values) in entries {
+            let mut pipe = redis::pipe();
+            for value in values {
+                pipe.rpush(key, value).ignore();
+            }
+            if let Some(ttl) = ttl_seconds {
+                pipe.expire(key, ttl as i64).ignore();
+            }
+
+            let mut conn = match self.conn().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    failures.push((key.clone(), e));
+                    continue;
+                }
+            };
+            match pipe.query_async::<_, ()>(&mut conn).await {
+                Ok(()) => self.release(conn).await,
+                Err(e) => failures.push((key.clone(), AppError::RedisError(e))),
+            }
+        }
+
+        Ok(failures)
+    }
+
+    async fn scan(&self, cursor: u64, pattern: &str, count: usize) -> Result<(u64, Vec<String>), AppError> {
+        let mut conn = self.conn().await?;
+        let result: redis::RedisResult<(u64, Vec<String>)> = redis::cmd("SCAN")
+            .cursor_arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(count)
+            .query_async(&mut conn)
+            .await;
+
+        match result {
+            Ok(page) => {
+                self.release(conn).await;
+                Ok(page)
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn try_acquire_lock(&self, lock_key: &str, ttl_ms: u64) -> Result<Option<String>, AppError> {
+        let mut conn = self.conn().await?;
+        let token = generate_lock_token();
+        let result: redis::RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(lock_key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl_ms)
+            .query_async(&mut conn)
+            .await;
+
+        match result {
+            Ok(set) => {
+                self.release(conn).await;
+                Ok(set.map(|_| token))
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn release_lock(&self, lock_key: &str, token: &str) -> Result<(), AppError> {
+        let mut conn = self.conn().await?;
+        match redis::Script::new(RELEASE_LOCK_SCRIPT)
+            .key(lock_key)
+            .arg(token)
+            .invoke_async::<_, ()>(&mut conn)
+            .await
+        {
+            Ok(()) => {
+                self.release(conn).await;
+                Ok(())
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+
+    async fn renew_lock(&self, lock_key: &str, token: &str, ttl_ms: u64) -> Result<(), AppError> {
+        let mut conn = self.conn().await?;
+        match redis::Script::new(RENEW_LOCK_SCRIPT)
+            .key(lock_key)
+            .arg(token)
+            .arg(ttl_ms)
+            .invoke_async::<_, ()>(&mut conn)
+            .await
+        {
+            Ok(()) => {
+                self.release(conn).await;
+                Ok(())
+            }
+            Err(e) => Err(AppError::RedisError(e)),
+        }
+    }
+}
+
+/// In-memory mock backend for tests: a `HashMap<String, VecDeque<String>>`
+/// guarded by a mutex, with simulated TTL expiry. Lets the full drain and
+/// cleanup pipelines - key parsing, batch writes, trimming/deletion, and
+/// the drainer's retry path - run end to end without a live Redis server.
+///
+/// Wraps its state in an `Arc` and derives `Clone` so it can sit behind
+/// `AppState<MockLogStore>` (state shared across handlers must be cheaply
+/// cloneable) while every clone still observes the same underlying data -
+/// the same way cloning `RedisLogStore` shares the same connection pool.
+#[derive(Clone, Default)]
+pub struct MockLogStore {
+    inner: std::sync::Arc<MockLogStoreInner>,
+}
+
+#[derive(Default)]
+struct MockLogStoreInner {
+    lists: StdMutex<HashMap<String, VecDeque<String>>>,
+    expirations: StdMutex<HashMap<String, Instant>>,
+    // Lets tests simulate a flaky connection: each call to `push` consumes
+    // one pending failure (if any) and returns a `retryable()` error
+    // instead of succeeding, so the retry-with-backoff path can be
+    // exercised deterministically.
+    pending_push_failures: StdMutex<u32>,
+    // Mirrors `RedisLogStore`'s `SET NX PX` / check-and-act Lua scripts
+    // closely enough that lock contention and renewal can actually be
+    // exercised against this mock, instead of always succeeding like the
+    // trait's default no-op lock methods.
+    locks: StdMutex<HashMap<String, LockState>>,
+}
+
+/// A held lock's token (who holds it) and expiry, as tracked by the mock.
+struct LockState {
+    token: String,
+    expires_at: Instant,
+}
+
+impl MockLogStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the next `count` calls to `push` fail with a simulated
+    /// (retryable) connection error instead of succeeding.
+    #[allow(dead_code)]  // Used by tests exercising the retry path
+    pub fn fail_next_pushes(&self, count: u32) {
+        *self.inner.pending_push_failures.lock().unwrap() = count;
+    }
+
+    /// Drop a key if its simulated TTL has elapsed
+    fn expire_if_needed(&self, key: &str) {
+        let expired = self
+            .inner
+            .expirations
+            .lock()
+            .unwrap()
+            .get(key)
+            .is_some_and(|deadline| Instant::now() >= *deadline);
+
+        if expired {
+            self.inner.lists.lock().unwrap().remove(key);
+            self.inner.expirations.lock().unwrap().remove(key);
+        }
+    }
+}
+
+/// Resolve Redis-style (possibly negative) start/stop indices into a
+/// concrete inclusive `[start, stop]` range over a list of length `len`,
+/// or `None` if the range is empty.
+///
+/// Matches real LRANGE/LTRIM semantics: a `start` past the end of the list
+/// is left as-is (not clamped back into range) so it ends up greater than
+/// `stop` and correctly yields an empty range, rather than wrongly
+/// snapping back to the last element.
+fn normalize_range(len: usize, start: isize, stop: isize) -> Option<(usize, usize)> {
+    if len == 0 {
+        return None;
+    }
+    let len_i = len as isize;
+    let start = if start < 0 { (len_i + start).max(0) } else { start };
+    let mut stop = if stop < 0 { len_i + stop } else { stop };
+    if stop >= len_i {
+        stop = len_i - 1;
+    }
+    if stop < 0 || start > stop {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+/// Minimal glob matcher supporting `*` wildcards, which is all the log
+/// pipeline's key patterns (e.g. `logs:user_*:*`) ever use.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    if !pattern.contains('*') {
+        return pattern == text;
+    }
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let mut pos = 0usize;
+    for (i, part) in parts.iter().enumerate() {
+        if part.is_empty() {
+            continue;
+        }
+        if i == 0 {
+            if !text[pos..].starts_with(part) {
+                return false;
+            }
+            pos += part.len();
+        } else if i == parts.len() - 1 {
+            return text[pos..].ends_with(part);
+        } else {
+            match text[pos..].find(part) {
+                Some(idx) => pos += idx + part.len(),
+                None => return false,
+            }
+        }
+    }
+    true
+}
+
+#[async_trait]
+impl LogStore for MockLogStore {
+    async fn push(&self, key: &str, value: &str) -> Result<(), AppError> {
+        {
+            let mut pending = self.inner.pending_push_failures.lock().unwrap();
+            if *pending > 0 {
+                *pending -= 1;
+                return Err(AppError::RedisError(redis::RedisError::from((
+                    redis::ErrorKind::IoError,
+                    "simulated connection failure",
+                ))));
+            }
+        }
+
+        self.inner
+            .lists
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .push_back(value.to_string());
+        Ok(())
+    }
+
+    async fn expire(&self, key: &str, ttl_seconds: u64) -> Result<(), AppError> {
+        self.inner
+            .expirations
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Instant::now() + Duration::from_secs(ttl_seconds));
+        Ok(())
+    }
+
+    async fn lrange(&self, key: &str, start: isize, stop: isize) -> Result<Vec<String>, AppError> {
+        self.expire_if_needed(key);
+        let lists = self.inner.lists.lock().unwrap();
+        let Some(list) = lists.get(key) else {
+            return Ok(Vec::new());
+        };
+        let Some((s, e)) = normalize_range(list.len(), start, stop) else {
+            return Ok(Vec::new());
+        };
+        Ok(list.iter().skip(s).take(e - s + 1).cloned().collect())
+    }
+
+    async fn ltrim(&self, key: &str, start: isize, stop: isize) -> Result<(), AppError> {
+        let mut lists = self.inner.lists.lock().unwrap();
+        if let Some(list) = lists.get_mut(key) {
+            match normalize_range(list.len(), start, stop) {
+                Some((s, e)) => {
+                    *list = list.iter().skip(s).take(e - s + 1).cloned().collect();
+                }
+                None => {
+                    list.clear();
+                }
+            }
+            if list.is_empty() {
+                lists.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    async fn del(&self, key: &str) -> Result<(), AppError> {
+        self.inner.lists.lock().unwrap().remove(key);
+        self.inner.expirations.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>, AppError> {
+        Ok(self
+            .inner
+            .lists
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|key| glob_match(pattern, key))
+            .cloned()
+            .collect())
+    }
+
+    async fn llen(&self, key: &str) -> Result<usize, AppError> {
+        self.expire_if_needed(key);
+        Ok(self.inner.lists.lock().unwrap().get(key).map_or(0, |list| list.len()))
+    }
+
+    async fn try_acquire_lock(&self, lock_key: &str, ttl_ms: u64) -> Result<Option<String>, AppError> {
+        let mut locks = self.inner.locks.lock().unwrap();
+        let now = Instant::now();
+
+        // Mirrors `SET NX PX`: only acquire if the key is absent or its
+        // previous holder's TTL has already elapsed
+        if locks.get(lock_key).is_some_and(|held| held.expires_at > now) {
+            return Ok(None);
+        }
+
+        let token = generate_lock_token();
+        locks.insert(
+            lock_key.to_string(),
+            LockState {
+                token: token.clone(),
+                expires_at: now + Duration::from_millis(ttl_ms),
+            },
+        );
+        Ok(Some(token))
+    }
+
+    async fn release_lock(&self, lock_key: &str, token: &str) -> Result<(), AppError> {
+        let mut locks = self.inner.locks.lock().unwrap();
+        // Mirrors `RELEASE_LOCK_SCRIPT`: only delete if we still hold it, so
+        // an expired-and-reacquired lock is never torn down by its former
+        // holder
+        if locks.get(lock_key).is_some_and(|held| held.token == token) {
+            locks.remove(lock_key);
+        }
+        Ok(())
+    }
+
+    async fn renew_lock(&self, lock_key: &str, token: &str, ttl_ms: u64) -> Result<(), AppError> {
+        let mut locks = self.inner.locks.lock().unwrap();
+        // Mirrors `RENEW_LOCK_SCRIPT`: only reset the TTL if we still hold
+        // the lock, for the same reason `release_lock` checks before
+        // deleting
+        if let Some(held) = locks.get_mut(lock_key) {
+            if held.token == token {
+                held.expires_at = Instant::now() + Duration::from_millis(ttl_ms);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_push_lrange_ltrim_roundtrip() {
+        let store = MockLogStore::new();
+        store.push("logs:user_1:1", "a").await.unwrap();
+        store.push("logs:user_1:1", "b").await.unwrap();
+        store.push("logs:user_1:1", "c").await.unwrap();
+
+        let batch = store.lrange("logs:user_1:1", 0, 1).await.unwrap();
+        assert_eq!(batch, vec!["a".to_string(), "b".to_string()]);
+
+        store.ltrim("logs:user_1:1", 2, -1).await.unwrap();
+        let remaining = store.lrange("logs:user_1:1", 0, -1).await.unwrap();
+        assert_eq!(remaining, vec!["c".to_string()]);
+
+        assert_eq!(store.llen("logs:user_1:1").await.unwrap(), 1);
+
+        store.ltrim("logs:user_1:1", 1, -1).await.unwrap();
+        assert_eq!(store.llen("logs:user_1:1").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_keys_and_scan_match_glob_pattern() {
+        let store = MockLogStore::new();
+        store.push("logs:user_1:100", "a").await.unwrap();
+        store.push("logs:user_2:100", "b").await.unwrap();
+        store.push("other:key", "c").await.unwrap();
+
+        let mut matched = store.keys("logs:user_*:*").await.unwrap();
+        matched.sort();
+        assert_eq!(matched, vec!["logs:user_1:100".to_string(), "logs:user_2:100".to_string()]);
+
+        let (next_cursor, scanned) = store.scan(0, "logs:user_*:*", 10).await.unwrap();
+        assert_eq!(next_cursor, 0);  // Mock always returns everything in one page
+        assert_eq!(scanned.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fail_next_pushes_returns_retryable_error() {
+        let store = MockLogStore::new();
+        store.fail_next_pushes(2);
+
+        let first = store.push("logs:user_1:1", "a").await;
+        assert!(first.is_err());
+        assert!(first.unwrap_err().retryable());
+
+        let second = store.push("logs:user_1:1", "a").await;
+        assert!(second.is_err());
+
+        // Third call's failure budget is exhausted - succeeds
+        store.push("logs:user_1:1", "a").await.unwrap();
+        assert_eq!(store.llen("logs:user_1:1").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_lock_blocks_concurrent_holder() {
+        let store = MockLogStore::new();
+        let lock_key = "lock:logs:user_1:1";
+
+        let first = store.try_acquire_lock(lock_key, 30_000).await.unwrap();
+        assert!(first.is_some(), "an unheld lock should be acquirable");
+
+        let second = store.try_acquire_lock(lock_key, 30_000).await.unwrap();
+        assert!(second.is_none(), "a held lock must block a concurrent acquire");
+
+        store.release_lock(lock_key, &first.unwrap()).await.unwrap();
+
+        let third = store.try_acquire_lock(lock_key, 30_000).await.unwrap();
+        assert!(third.is_some(), "the lock should be acquirable again once released");
+    }
+
+    #[tokio::test]
+    async fn test_renew_lock_rejects_mismatched_token() {
+        let store = MockLogStore::new();
+        let lock_key = "lock:logs:user_1:1";
+        let token = store.try_acquire_lock(lock_key, 10_000).await.unwrap().unwrap();
+
+        // A foreign/stale token must not be able to touch a lock it doesn't hold
+        store.renew_lock(lock_key, "someone-elses-token", 10_000).await.unwrap();
+
+        // Releasing with the *real* token still works - proving the
+        // mismatched renew above didn't corrupt or steal the real token
+        store.release_lock(lock_key, &token).await.unwrap();
+        assert!(store.try_acquire_lock(lock_key, 10_000).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_renew_lock_extends_ttl_past_original_expiry() {
+        let store = MockLogStore::new();
+        let lock_key = "lock:logs:user_1:1";
+        let token = store.try_acquire_lock(lock_key, 40).await.unwrap().unwrap();
+
+        // Renew well before the short original TTL elapses
+        store.renew_lock(lock_key, &token, 10_000).await.unwrap();
+
+        // Sleep past the *original* TTL - if renewal hadn't taken effect,
+        // the lock would already be free again here
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(
+            store.try_acquire_lock(lock_key, 10_000).await.unwrap().is_none(),
+            "a renewed lock must still be held well past its original TTL"
+        );
+    }
+}
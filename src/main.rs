@@ -3,22 +3,27 @@ mod types;
 mod file_redis_layer;
 mod drainer;
 mod config;
+mod dlock;
+mod log_store;
 mod rate_limit;
 
 use axum::{
     extract::{Json, State},
     http::StatusCode,
     middleware,
+    response::Json as ResponseJson,
     routing::post,
     Router,
 };
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 
 // Import our modules
 use types::{AppError, AppState, LogEvent};
-use file_redis_layer::write_to_cache;
+use file_redis_layer::{create_redis_pool, write_batch_to_cache, write_to_cache};
 use config::Config;
+use log_store::RedisLogStore;
 use rate_limit::rate_limit_middleware;
 
 // ============================================
@@ -33,16 +38,35 @@ async fn handle_log(
 ) -> Result<StatusCode, AppError> {
     // Write to Redis cache - this is instant!
     write_to_cache(
-        &state.redis_client,
+        &state.log_store,
         &payload,
         state.config.redis.key_expiration_seconds,
         state.config.redis.disable_ttl,
     )
     .await?;
-    
+
     Ok(StatusCode::OK)
 }
 
+/// Batch version of `handle_log` - writes many events with one pipelined
+/// round trip per Redis key instead of one RPUSH/EXPIRE pair per event. Returns the
+/// number of entries written per Redis key so the client can confirm
+/// ingestion.
+async fn handle_log_batch(
+    State(state): State<AppState>,
+    Json(payload): Json<Vec<LogEvent>>,
+) -> Result<ResponseJson<HashMap<String, usize>>, AppError> {
+    let counts = write_batch_to_cache(
+        &state.log_store,
+        &payload,
+        state.config.redis.key_expiration_seconds,
+        state.config.redis.disable_ttl,
+    )
+    .await?;
+
+    Ok(ResponseJson(counts))
+}
+
 // ============================================
 // MAIN
 // ============================================
@@ -51,49 +75,52 @@ async fn handle_log(
 async fn main() {
     // Load configuration
     let config = Config::load();
-    
-    // Connect to Redis
-    let redis_client = redis::Client::open(config.redis.url.as_str())
-        .expect("Failed to connect to Redis");
-    
+
+    // Create a pooled Redis connection manager - avoids paying connection
+    // setup cost on every request/drain cycle
+    let redis_pool = create_redis_pool(&config.redis);
+
     // Test connection
-    let _test_conn = redis_client
-        .get_async_connection()
+    let _test_conn = redis_pool
+        .get()
         .await
-        .expect("Failed to get Redis connection");
-    
+        .expect("Failed to get Redis connection from pool");
+
     println!("✅ Connected to Redis at {}", config.redis.url);
-    
+
     // Create rate limiter
     let rate_limiter = Arc::new(rate_limit::RateLimiter::new(
         config.server.rate_limit.clone(),
     ));
-    
+
     // Create app state
     let state = AppState {
-        redis_client: Arc::new(redis_client.clone()),
+        redis_pool: redis_pool.clone(),
+        log_store: RedisLogStore::new(redis_pool),
         config: config.clone(),
         rate_limiter: rate_limiter.clone(),
     };
-    
+
     // Note: Drainer is now a separate service
     // Run it with: cargo run --bin drainer
     // This allows the drainer to be scaled independently
-    
+
     // Create router with rate limiting middleware
     let app = Router::new()
         .route("/log", post(handle_log))
+        .route("/log/batch", post(handle_log_batch))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             rate_limit_middleware,
         ))
         .with_state(state);  // Share state with handlers
-    
+
     // Start server
     let addr = format!("{}:{}", config.server.host, config.server.port);
     let listener = TcpListener::bind(&addr).await.unwrap();
     println!("🚀 Server running on http://{}", addr);
     println!("📝 POST /log - Writes to Redis cache (fast!)");
+    println!("📝 POST /log/batch - Writes many events, one pipelined round trip per key");
     println!("\n💡 Architecture:");
     println!("   1. Write → Redis (instant, in-memory)");
     println!("   2. Separate drainer service → Files (run with: cargo run --bin drainer)");
@@ -110,6 +137,6 @@ async fn main() {
     println!("\n🔄 To start the drainer service:");
     println!("   cargo run --bin drainer");
     println!("   • main.rs - API server");
-    
+
     axum::serve(listener, app).await.unwrap();
 }
@@ -4,70 +4,190 @@ use axum::{
     http::{HeaderMap, StatusCode},
     response::Response,
 };
-use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
 use crate::config::RateLimitConfig;
 use crate::types::AppState;
 
-/// Rate limiter using token bucket algorithm
-#[derive(Clone)]
-pub struct RateLimiter {
-    config: RateLimitConfig,
-    // Simple in-memory rate limiter (per IP would need a HashMap)
-    // For production, use Redis-based rate limiting
-    tokens: Arc<Mutex<TokenBucket>>,
+/// Fixed-window size backing `requests_per_minute`
+const WINDOW_SECONDS: u64 = 60;
+
+/// How many local increments a key accumulates before we reconcile with Redis
+const LOCAL_SYNC_THRESHOLD: u32 = 10;
+
+/// Local cache entries untouched for this long are swept out - long enough
+/// to outlive reuse within a window, short enough that a client varying its
+/// rate-limit key (e.g. spoofing `x-forwarded-for`) per request can't grow
+/// the map forever.
+const LOCAL_ENTRY_TTL_SECONDS: u64 = WINDOW_SECONDS * 2;
+
+/// Hard cap on the local cache's size - a backstop in case entries are
+/// created faster than the TTL sweep below reclaims them.
+const MAX_LOCAL_ENTRIES: usize = 10_000;
+
+/// Outcome of a rate-limit check
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitDecision {
+    Allowed,
+    RetryAt(Instant),
+    RetryNever,
 }
 
-struct TokenBucket {
-    tokens: u32,
-    last_refill: SystemTime,
-    requests_per_minute: u32,
-    burst_size: u32,
+/// Per-key local cache entry
+struct CacheEntry {
+    // Approximate request count observed locally since the last Redis sync
+    approx_count: AtomicU32,
+    // If set and still in the future, this key is known to be over limit
+    // without needing to ask Redis again
+    over_limit_until: Option<Instant>,
+    // Last time this entry was touched by `check()` - drives the TTL sweep
+    last_seen: Instant,
 }
 
-impl RateLimiter {
+/// Distributed rate limiter keyed by an arbitrary key (IP today, user_id
+/// later). Layers a small in-process cache over Redis fixed-window counters
+/// so most requests never have to make a Redis round-trip: a key that's
+/// already known to be over limit is rejected locally, and a key under
+/// limit only syncs with Redis occasionally rather than on every request.
+pub struct DeferredRateLimiter<K> {
+    config: RateLimitConfig,
+    local: Mutex<HashMap<K, CacheEntry>>,
+}
+
+impl<K> DeferredRateLimiter<K>
+where
+    K: Eq + Hash + Clone + Display,
+{
     pub fn new(config: RateLimitConfig) -> Self {
         Self {
-            config: config.clone(),
-            tokens: Arc::new(Mutex::new(TokenBucket {
-                tokens: config.burst_size,
-                last_refill: SystemTime::now(),
-                requests_per_minute: config.requests_per_minute,
-                burst_size: config.burst_size,
-            })),
+            config,
+            local: Mutex::new(HashMap::new()),
         }
     }
 
-    /// Check if request is allowed (token bucket algorithm)
-    pub async fn check(&self) -> bool {
-        let mut bucket = self.tokens.lock().await;
-        let now = SystemTime::now();
-        
-        // Calculate time since last refill
-        let elapsed = now
-            .duration_since(bucket.last_refill)
-            .unwrap_or(Duration::from_secs(0));
-        
-        // Refill tokens based on elapsed time
-        // Refill rate: requests_per_minute / 60 seconds
-        if elapsed.as_secs() > 0 {
-            let tokens_to_add = (bucket.requests_per_minute as u64 * elapsed.as_secs()) / 60;
-            bucket.tokens = (bucket.tokens + tokens_to_add as u32).min(bucket.burst_size);
-            bucket.last_refill = now;
+    /// Bound the local cache: drop entries untouched for longer than
+    /// `LOCAL_ENTRY_TTL_SECONDS`, then - if a burst of distinct keys still
+    /// has it sitting at `MAX_LOCAL_ENTRIES` - evict the least-recently-seen
+    /// entries until back under the cap. Without this, a client varying its
+    /// rate-limit key per request (e.g. spoofing `x-forwarded-for`) could
+    /// grow this map without bound.
+    fn evict_stale(local: &mut HashMap<K, CacheEntry>) {
+        let now = Instant::now();
+        local.retain(|_, entry| {
+            now.duration_since(entry.last_seen) < Duration::from_secs(LOCAL_ENTRY_TTL_SECONDS)
+        });
+
+        while local.len() >= MAX_LOCAL_ENTRIES {
+            let Some(oldest_key) = local
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_seen)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            local.remove(&oldest_key);
         }
-        
-        // Check if we have tokens
-        if bucket.tokens > 0 {
-            bucket.tokens -= 1;
-            true
-        } else {
-            false
+    }
+
+    /// Check whether a request for `key` is allowed.
+    /// Falls open (allows the request) if Redis is unreachable, since a
+    /// rate limiter should degrade gracefully rather than take the service
+    /// down with it.
+    pub async fn check(&self, key: K, redis_pool: &deadpool_redis::Pool) -> RateLimitDecision {
+        if self.config.requests_per_minute == 0 {
+            return RateLimitDecision::RetryNever;
         }
+
+        // Fast path: a key already known to be over limit is rejected
+        // without touching Redis at all
+        {
+            let mut local = self.local.lock().await;
+            if let Some(entry) = local.get_mut(&key) {
+                entry.last_seen = Instant::now();
+                if let Some(until) = entry.over_limit_until {
+                    if Instant::now() < until {
+                        return RateLimitDecision::RetryAt(until);
+                    }
+                }
+            }
+        }
+
+        // Bump the local approximate counter; only reconcile with Redis
+        // when this key first appears locally or crosses the sync threshold,
+        // adding every request counted locally since the previous sync
+        let sync_delta = {
+            let mut local = self.local.lock().await;
+            Self::evict_stale(&mut local);
+            let now = Instant::now();
+            let entry = local.entry(key.clone()).or_insert_with(|| CacheEntry {
+                approx_count: AtomicU32::new(0),
+                over_limit_until: None,
+                last_seen: now,
+            });
+            entry.last_seen = now;
+            let count = entry.approx_count.fetch_add(1, Ordering::Relaxed) + 1;
+            if count == 1 {
+                Some(1)
+            } else if count % LOCAL_SYNC_THRESHOLD == 0 {
+                // The sync at count 1 already covered that first request
+                Some(if count == LOCAL_SYNC_THRESHOLD { LOCAL_SYNC_THRESHOLD - 1 } else { LOCAL_SYNC_THRESHOLD })
+            } else {
+                None
+            }
+        };
+
+        let Some(delta) = sync_delta else {
+            return RateLimitDecision::Allowed;
+        };
+
+        let mut conn = match redis_pool.get().await {
+            Ok(conn) => conn,
+            Err(_) => return RateLimitDecision::Allowed,
+        };
+
+        let redis_key = format!("ratelimit:{}", key);
+        match increment_window(&mut conn, &redis_key, delta as u64).await {
+            Ok(count) if count > self.config.requests_per_minute as u64 => {
+                let ttl: i64 = conn.ttl(&redis_key).await.unwrap_or(WINDOW_SECONDS as i64);
+                let retry_at = Instant::now() + Duration::from_secs(ttl.max(0) as u64);
+
+                let mut local = self.local.lock().await;
+                if let Some(entry) = local.get_mut(&key) {
+                    entry.over_limit_until = Some(retry_at);
+                }
+
+                RateLimitDecision::RetryAt(retry_at)
+            }
+            Ok(_) => RateLimitDecision::Allowed,
+            Err(_) => RateLimitDecision::Allowed,
+        }
+    }
+}
+
+/// `INCRBY` the fixed-window counter by `by`, setting `EXPIRE` only on the
+/// first increment of the window so the window resets every `WINDOW_SECONDS`.
+async fn increment_window(
+    conn: &mut deadpool_redis::Connection,
+    key: &str,
+    by: u64,
+) -> redis::RedisResult<u64> {
+    let count: u64 = conn.incr(key, by).await?;
+    if count == by {
+        conn.expire::<_, ()>(key, WINDOW_SECONDS as i64).await?;
     }
+    Ok(count)
 }
 
+/// Per-IP rate limiter used by the API today; `DeferredRateLimiter` is
+/// generic so a later `user_id`-keyed limiter can reuse the same cache.
+pub type RateLimiter = DeferredRateLimiter<String>;
+
 /// Rate limiting middleware
 pub async fn rate_limit_middleware(
     State(state): State<AppState>,
@@ -76,28 +196,91 @@ pub async fn rate_limit_middleware(
     next: axum::middleware::Next,
 ) -> Response {
     // Extract IP from headers (for per-IP rate limiting)
-    let _ip = headers
+    let ip = headers
         .get("x-forwarded-for")
         .or_else(|| headers.get("x-real-ip"))
         .and_then(|h| h.to_str().ok())
-        .unwrap_or("unknown");
-    
-    // Use rate limiter from state
-    if !state.rate_limiter.check().await {
-        return Response::builder()
-            .status(StatusCode::TOO_MANY_REQUESTS)
-            .header("x-ratelimit-limit", state.config.server.rate_limit.requests_per_minute.to_string())
-            .header("retry-after", "60")
-            .header("content-type", "application/json")
-            .body(axum::body::Body::from(
-                serde_json::json!({
-                    "error": "Rate limit exceeded",
-                    "details": format!("Maximum {} requests per minute", state.config.server.rate_limit.requests_per_minute)
-                }).to_string()
-            ))
-            .unwrap()
-            .into();
+        .unwrap_or("unknown")
+        .to_string();
+
+    match state.rate_limiter.check(ip, &state.redis_pool).await {
+        RateLimitDecision::Allowed => next.run(request).await,
+        decision => rate_limited_response(&state, decision),
+    }
+}
+
+fn rate_limited_response(state: &AppState, decision: RateLimitDecision) -> Response {
+    let mut builder = Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(
+            "x-ratelimit-limit",
+            state.config.server.rate_limit.requests_per_minute.to_string(),
+        )
+        .header("content-type", "application/json");
+
+    if let RateLimitDecision::RetryAt(instant) = decision {
+        let retry_after = instant.saturating_duration_since(Instant::now()).as_secs();
+        builder = builder.header("retry-after", retry_after.to_string());
+    }
+
+    builder
+        .body(axum::body::Body::from(
+            serde_json::json!({
+                "error": "Rate limit exceeded",
+                "details": format!("Maximum {} requests per minute", state.config.server.rate_limit.requests_per_minute)
+            }).to_string()
+        ))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pool pointed at a bogus address. Building it never connects -
+    /// `deadpool_redis` only dials out on the first `.get()` - so tests that
+    /// must never reach Redis can pass this and fail loudly if they do.
+    fn unreachable_pool() -> deadpool_redis::Pool {
+        deadpool_redis::Config::from_url("redis://127.0.0.1:1".to_string())
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .expect("pool construction doesn't connect eagerly")
+    }
+
+    #[tokio::test]
+    async fn test_requests_per_minute_zero_always_retries_never() {
+        let limiter: RateLimiter = DeferredRateLimiter::new(RateLimitConfig {
+            requests_per_minute: 0,
+            burst_size: 0,
+        });
+
+        let decision = limiter.check("1.2.3.4".to_string(), &unreachable_pool()).await;
+        assert!(matches!(decision, RateLimitDecision::RetryNever));
+    }
+
+    #[tokio::test]
+    async fn test_local_cache_rejects_over_limit_key_without_touching_redis() {
+        let limiter: RateLimiter = DeferredRateLimiter::new(RateLimitConfig {
+            requests_per_minute: 100,
+            burst_size: 20,
+        });
+        let key = "5.6.7.8".to_string();
+
+        {
+            let mut local = limiter.local.lock().await;
+            local.insert(
+                key.clone(),
+                CacheEntry {
+                    approx_count: AtomicU32::new(1),
+                    over_limit_until: Some(Instant::now() + Duration::from_secs(30)),
+                    last_seen: Instant::now(),
+                },
+            );
+        }
+
+        // Pointed at a pool that can't actually connect - if this path fell
+        // through to Redis instead of rejecting locally, it would error out
+        // (or hang) rather than return `RetryAt`.
+        let decision = limiter.check(key, &unreachable_pool()).await;
+        assert!(matches!(decision, RateLimitDecision::RetryAt(_)));
     }
-    
-    next.run(request).await
 }
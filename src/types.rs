@@ -21,7 +21,65 @@ pub enum AppError {
     JsonParseError(String),
     FileError(String),
     SerializationError(String),
-    RedisError(String),
+    // Invalid input that isn't itself a Redis failure (e.g. a malformed
+    // cache key) - kept distinct from `RedisError` so `retryable()` doesn't
+    // have to guess at a bare string's origin.
+    ParseError(String),
+    // Wraps the real `redis::RedisError` (rather than a stringified
+    // message) so callers can inspect its kind - e.g. `retryable()` below
+    // distinguishes a transient connection drop from a permanent command
+    // error.
+    RedisError(redis::RedisError),
+    // Failure to check a connection out of the pool itself (exhausted,
+    // timed out waiting, or the recycle/create hook failed) - distinct from
+    // `RedisError` since it never reaches the point of running a command.
+    PoolError(deadpool_redis::PoolError),
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::JsonParseError(msg) => write!(f, "invalid JSON: {}", msg),
+            AppError::FileError(msg) => write!(f, "file operation failed: {}", msg),
+            AppError::SerializationError(msg) => write!(f, "serialization failed: {}", msg),
+            AppError::ParseError(msg) => write!(f, "parse error: {}", msg),
+            AppError::RedisError(e) => write!(f, "redis error: {}", e),
+            AppError::PoolError(e) => write!(f, "redis pool error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::RedisError(e) => Some(e),
+            AppError::PoolError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl AppError {
+    /// Whether retrying the operation that produced this error is likely to
+    /// succeed. Only `RedisError` carries enough information to tell:
+    /// connection-level failures (dropped connection, refused connection,
+    /// timeout, I/O) are usually transient and worth retrying; command
+    /// errors (wrong type, bad arguments) will just fail again.
+    pub fn retryable(&self) -> bool {
+        match self {
+            AppError::RedisError(e) => {
+                e.is_connection_dropped()
+                    || e.is_connection_refusal()
+                    || e.is_timeout()
+                    || e.kind() == redis::ErrorKind::IoError
+            }
+            // The pool itself was briefly exhausted or a connection was
+            // slow to establish/recycle - worth a retry rather than failing
+            // the request outright.
+            AppError::PoolError(_) => true,
+            _ => false,
+        }
+    }
 }
 
 // Error response struct
@@ -50,10 +108,20 @@ impl axum::response::IntoResponse for AppError {
                 "Serialization failed".to_string(),
                 format!("Could not serialize data: {}", msg),
             ),
-            AppError::RedisError(msg) => (
+            AppError::ParseError(msg) => (
+                StatusCode::BAD_REQUEST,
+                "Invalid input".to_string(),
+                msg,
+            ),
+            AppError::RedisError(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Cache operation failed".to_string(),
+                format!("Redis error: {}", e),
+            ),
+            AppError::PoolError(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Cache operation failed".to_string(),
-                format!("Redis error: {}", msg),
+                format!("Redis pool error: {}", e),
             ),
         };
 
@@ -66,9 +134,20 @@ impl axum::response::IntoResponse for AppError {
 }
 
 // Application state - shared across all handlers
+//
+// Generic over `LogStore` (defaulting to the real Redis-backed
+// implementation) so the state can be built around `MockLogStore` in
+// tests. The handlers in main.rs and the rate-limit middleware take the
+// default `AppState<RedisLogStore>`; the drainer and cleanup service don't
+// use `AppState` and are generic over `LogStore` themselves.
 #[derive(Clone)]
-pub struct AppState {
-    pub redis_client: Arc<redis::Client>,
+pub struct AppState<S: crate::log_store::LogStore = crate::log_store::RedisLogStore> {
+    // Used by the rate limiter for its own Redis counters
+    pub redis_pool: deadpool_redis::Pool,
+    // LogStore wrapper used by the ingest handlers (real Redis in
+    // production; swappable for the in-memory mock in tests)
+    pub log_store: S,
     pub config: crate::config::Config,
+    pub rate_limiter: Arc<crate::rate_limit::RateLimiter>,
 }
 
@@ -3,8 +3,9 @@
 // Run with: cargo test --test api_test -- --ignored
 
 use log_pipelines::types::{AppState, LogEvent};
-use log_pipelines::file_redis_layer::write_to_cache;
+use log_pipelines::file_redis_layer::{create_redis_pool, write_to_cache};
 use log_pipelines::config::Config;
+use log_pipelines::log_store::RedisLogStore;
 use std::sync::Arc;
 
 #[tokio::test]
@@ -12,37 +13,37 @@ use std::sync::Arc;
 async fn test_api_log_endpoint() {
     // This test requires Redis to be running
     // You can run it with: cargo test --test api_test -- --ignored
-    
+
     let config = Config::default();
-    let redis_client = redis::Client::open(config.redis.url.as_str())
-        .expect("Failed to connect to Redis");
-    
+    let redis_pool = create_redis_pool(&config.redis);
+
     let rate_limiter = Arc::new(log_pipelines::rate_limit::RateLimiter::new(
         config.server.rate_limit.clone(),
     ));
-    
+
     let state = AppState {
-        redis_client: Arc::new(redis_client),
+        redis_pool: redis_pool.clone(),
+        log_store: RedisLogStore::new(redis_pool),
         config: config.clone(),
         rate_limiter,
     };
-    
+
     // Create test event
     let event = LogEvent {
         user_id: "test_user".to_string(),
         event: "test_event".to_string(),
         timestamp: 1712345678,
     };
-    
+
     // Test writing to cache
     let result = write_to_cache(
-        &state.redis_client,
+        &state.log_store,
         &event,
         config.redis.key_expiration_seconds,
         config.redis.disable_ttl,
     )
     .await;
-    
+
     assert!(result.is_ok(), "Should successfully write to Redis");
 }
 